@@ -1,13 +1,59 @@
 use std::fs::File;
+use std::io;
 use std::io::BufReader;
 use std::io::Read;
+use std::fmt;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::sync::Mutex;
 
 use rustc_serialize::json::{self, Json};
 use rustc_serialize::Decodable;
 
 use git2::{Oid, Repository};
+use sled::Db;
+use lru::LruCache;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // Failed to read the config file itself, naming its path.
+    Io(String, io::Error),
+    // The config file wasn't valid JSON.
+    JsonParse(String, json::ParserError),
+    // The JSON was valid but didn't have the shape we expect; names the
+    // tree (if any) and field at fault. When a tree's paths fail to decode,
+    // `field` holds the decoder's error message instead, which names the
+    // field only when the field is missing.
+    Shape { tree: Option<String>, field: String },
+    // `git2::Repository::open` failed, naming the path we tried to open.
+    GitOpen(String, git2::Error),
+    // A blame-repo commit's message didn't match the `<marker> <oid> [hg
+    // <rev>]` convention `index_blame` expects; names the offending commit.
+    BlameEntry(Oid, String),
+    // The blame repo at this path has no HEAD commit to index from (e.g. an
+    // empty or unborn repo).
+    BlameRepoHead(String),
+    // Failed to open the sled store backing the blame index, naming its
+    // path (next to the blame repo).
+    BlameStoreOpen(String, sled::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ConfigError::Io(ref path, ref err) => write!(f, "error reading {}: {}", path, err),
+            &ConfigError::JsonParse(ref path, ref err) => write!(f, "{} is not valid JSON: {}", path, err),
+            &ConfigError::Shape { ref tree, ref field } => match tree {
+                &Some(ref tree) => write!(f, "tree `{}` is missing or has a malformed `{}`", tree, field),
+                &None => write!(f, "config is missing or has a malformed `{}`", field),
+            },
+            &ConfigError::GitOpen(ref path, ref err) => write!(f, "failed to open git repository at {}: {}", path, err),
+            &ConfigError::BlameEntry(oid, ref reason) => write!(f, "malformed blame entry for commit {}: {}", oid, reason),
+            &ConfigError::BlameRepoHead(ref path) => write!(f, "blame repo at {} has no HEAD commit to index from", path),
+            &ConfigError::BlameStoreOpen(ref path, ref err) => write!(f, "failed to open blame index store at {}: {}", path, err),
+        }
+    }
+}
 
 #[derive(RustcDecodable, RustcEncodable)]
 pub struct TreeConfigPaths {
@@ -17,16 +63,299 @@ pub struct TreeConfigPaths {
     pub git_blame_path: Option<String>,
     pub objdir_path: String,
     pub hg_root: Option<String>,
+    // Path to a git-cinnabar-converted clone of `git_path`, used instead of
+    // `git_blame_path`'s synthetic commit messages to resolve the Hg rev for
+    // a given git OID. See `read_cinnabar_hg_map`.
+    pub cinnabar_path: Option<String>,
+
+    // When set (instead of `git_path`), tree/blob data is fetched from a
+    // remote host's git data API rather than a local clone. See
+    // `GitHubBackend`.
+    pub github_source: Option<GitHubConfig>,
+
+    // Additional blame/Hg-rev sources to consult, in order, after the
+    // primary one built from `git_blame_path`/`cinnabar_path`. Lets a tree
+    // stitch together several upstreams, e.g. per-subdirectory blame repos
+    // for vendored code brought in like a cargo `vendor` tree.
+    pub extra_blame_sources: Option<Vec<BlameSourceConfig>>,
 }
 
-pub struct GitData {
-    pub repo: Repository,
+#[derive(RustcDecodable, RustcEncodable)]
+pub struct BlameSourceConfig {
+    pub git_blame_path: Option<String>,
+    pub cinnabar_path: Option<String>,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable)]
+pub struct GitHubConfig {
+    pub owner: String,
+    pub repo: String,
+    pub base_url: String,
+    pub token: Option<String>,
+}
+
+// A single tree entry as returned by `GitBackend::read_tree`.
+pub struct TreeEntry {
+    pub name: String,
+    pub oid: Oid,
+    pub is_tree: bool,
+}
+
+// Failure reading git object data through a `GitBackend`. Distinct from
+// `ConfigError`: this is a per-call, per-object error (a single blob/tree/ref
+// lookup going wrong), not a tree-load-time error, so callers that walk many
+// objects can catch and skip/log rather than aborting the whole operation.
+#[derive(Debug)]
+pub enum GitBackendError {
+    Git(git2::Error),
+    // A remote request failed, returned a non-success status, or its body
+    // wasn't shaped like the data we asked for; carries a message describing
+    // what went wrong and for which URL/object.
+    Remote(String),
+    // GitHub truncates `git/trees/{sha}?recursive=1` once a tree exceeds its
+    // size limits (~100k entries/7MB); we have no way to recover the rest of
+    // the tree from that response, so this surfaces rather than silently
+    // serving partial data.
+    Truncated(Oid),
+}
+
+impl fmt::Display for GitBackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GitBackendError::Git(ref err) => write!(f, "{}", err),
+            &GitBackendError::Remote(ref msg) => write!(f, "{}", msg),
+            &GitBackendError::Truncated(oid) => write!(f, "tree {} was truncated by the remote", oid),
+        }
+    }
+}
+
+// Everything mozsearch needs to read git object data for a tree. `Repository`
+// (a local on-disk clone) is one implementation; `GitHubBackend` is another,
+// letting us index and serve trees that are never cloned locally.
+//
+// Only `Send`, not `Sync`: `git2::Repository` does no internal locking and
+// isn't `Sync`, so a bound of `Sync` here would make `impl GitBackend for
+// Repository` impossible to satisfy. `GitHubBackend` keeps its own cache
+// behind a `Mutex` so it stays safely usable from one owning thread at a
+// time either way.
+pub trait GitBackend: Send {
+    fn read_blob(&self, oid: Oid) -> Result<Vec<u8>, GitBackendError>;
+    fn read_tree(&self, oid: Oid, recursive: bool) -> Result<Vec<TreeEntry>, GitBackendError>;
+    fn resolve_ref(&self, name: &str) -> Result<Oid, GitBackendError>;
+}
+
+impl GitBackend for Repository {
+    fn read_blob(&self, oid: Oid) -> Result<Vec<u8>, GitBackendError> {
+        let blob = self.find_blob(oid).map_err(GitBackendError::Git)?;
+        Ok(blob.content().to_owned())
+    }
+
+    fn read_tree(&self, oid: Oid, recursive: bool) -> Result<Vec<TreeEntry>, GitBackendError> {
+        let tree = self.find_tree(oid).map_err(GitBackendError::Git)?;
+        let mut entries = Vec::new();
+        collect_tree_entries(self, &tree, recursive, &mut entries)?;
+        Ok(entries)
+    }
+
+    fn resolve_ref(&self, name: &str) -> Result<Oid, GitBackendError> {
+        self.refname_to_id(name).map_err(GitBackendError::Git)
+    }
+}
+
+fn collect_tree_entries(repo: &Repository, tree: &git2::Tree, recursive: bool, out: &mut Vec<TreeEntry>) -> Result<(), GitBackendError> {
+    for entry in tree.iter() {
+        let is_tree = entry.kind() == Some(git2::ObjectType::Tree);
+        let name = entry.name()
+            .ok_or_else(|| GitBackendError::Git(git2::Error::from_str("tree entry has a non-UTF-8 name")))?;
+        out.push(TreeEntry {
+            name: name.to_owned(),
+            oid: entry.id(),
+            is_tree: is_tree,
+        });
+        if recursive && is_tree {
+            let object = entry.to_object(repo).map_err(GitBackendError::Git)?;
+            let subtree = object.into_tree()
+                .map_err(|_| GitBackendError::Git(git2::Error::from_str("tree entry marked as a tree is not one")))?;
+            collect_tree_entries(repo, &subtree, recursive, out)?;
+        }
+    }
+    Ok(())
+}
+
+// Capacity of `GitHubBackend`'s fetched-object cache, a single LRU cache that
+// holds blobs and trees alike. Bounds how many remote round-trips a
+// long-lived index/web process needs to make for repeatedly-visited objects.
+const GITHUB_CACHE_CAPACITY: usize = 8192;
+
+enum CachedObject {
+    Blob(Vec<u8>),
+    Tree(Vec<TreeEntry>),
+}
+
+impl Clone for TreeEntry {
+    fn clone(&self) -> TreeEntry {
+        TreeEntry {
+            name: self.name.clone(),
+            oid: self.oid,
+            is_tree: self.is_tree,
+        }
+    }
+}
+
+impl Clone for CachedObject {
+    fn clone(&self) -> CachedObject {
+        match self {
+            &CachedObject::Blob(ref content) => CachedObject::Blob(content.clone()),
+            &CachedObject::Tree(ref entries) => CachedObject::Tree(entries.clone()),
+        }
+    }
+}
+
+// Serves tree/blob data from a remote host's git data API (modeled on
+// GitHub's `GET .../git/trees/{sha}` and `GET .../git/blobs/{sha}`) instead
+// of a local clone, so mozsearch can index repositories it never checks out.
+pub struct GitHubBackend {
+    config: GitHubConfig,
+    client: reqwest::blocking::Client,
+    cache: Mutex<LruCache<Oid, CachedObject>>,
+}
+
+impl GitHubBackend {
+    pub fn new(config: GitHubConfig) -> GitHubBackend {
+        GitHubBackend {
+            config: config,
+            client: reqwest::blocking::Client::new(),
+            cache: Mutex::new(LruCache::new(GITHUB_CACHE_CAPACITY)),
+        }
+    }
+
+    fn get_json(&self, path: &str) -> Result<Json, GitBackendError> {
+        let url = format!("{}/repos/{}/{}{}", self.config.base_url, self.config.owner, self.config.repo, path);
+        let mut request = self.client.get(&url);
+        if let Some(ref token) = self.config.token {
+            request = request.header("Authorization", format!("token {}", token));
+        }
+        let response = request.send()
+            .map_err(|e| GitBackendError::Remote(format!("request to {} failed: {}", url, e)))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(GitBackendError::Remote(format!("{} returned {}", url, status)));
+        }
+        let body = response.text()
+            .map_err(|e| GitBackendError::Remote(format!("failed to read response body from {}: {}", url, e)))?;
+        Json::from_str(&body)
+            .map_err(|e| GitBackendError::Remote(format!("{} returned invalid JSON: {}", url, e)))
+    }
+}
+
+impl GitBackend for GitHubBackend {
+    fn read_blob(&self, oid: Oid) -> Result<Vec<u8>, GitBackendError> {
+        if let Some(&CachedObject::Blob(ref content)) = self.cache.lock().unwrap().get(&oid) {
+            return Ok(content.clone());
+        }
+
+        let json = self.get_json(&format!("/git/blobs/{}", oid))?;
+        let obj = json.as_object()
+            .ok_or_else(|| GitBackendError::Remote(format!("blob {} response was not a JSON object", oid)))?;
+        let content_b64 = obj.get("content").and_then(Json::as_string)
+            .ok_or_else(|| GitBackendError::Remote(format!("blob {} response is missing `content`", oid)))?
+            .replace("\n", "");
+        let content = base64::decode(&content_b64)
+            .map_err(|e| GitBackendError::Remote(format!("blob {} has invalid base64 content: {}", oid, e)))?;
+
+        self.cache.lock().unwrap().put(oid, CachedObject::Blob(content.clone()));
+        Ok(content)
+    }
+
+    fn read_tree(&self, oid: Oid, recursive: bool) -> Result<Vec<TreeEntry>, GitBackendError> {
+        if let Some(&CachedObject::Tree(ref entries)) = self.cache.lock().unwrap().get(&oid) {
+            return Ok(entries.clone());
+        }
+
+        let path = if recursive {
+            format!("/git/trees/{}?recursive=1", oid)
+        } else {
+            format!("/git/trees/{}", oid)
+        };
+        let json = self.get_json(&path)?;
+        let obj = json.as_object()
+            .ok_or_else(|| GitBackendError::Remote(format!("tree {} response was not a JSON object", oid)))?;
+
+        // `recursive=1` is silently truncated by GitHub once a tree exceeds
+        // ~100k entries/7MB; we have no way to fetch the missing entries
+        // from this response, so surface it rather than indexing a partial
+        // tree.
+        if obj.get("truncated").and_then(Json::as_boolean).unwrap_or(false) {
+            return Err(GitBackendError::Truncated(oid));
+        }
+
+        let tree_entries = obj.get("tree").and_then(Json::as_array)
+            .ok_or_else(|| GitBackendError::Remote(format!("tree {} response is missing `tree`", oid)))?;
+
+        let mut entries = Vec::with_capacity(tree_entries.len());
+        for entry in tree_entries {
+            let entry = entry.as_object()
+                .ok_or_else(|| GitBackendError::Remote(format!("tree {} has a malformed entry", oid)))?;
+            let name = entry.get("path").and_then(Json::as_string)
+                .ok_or_else(|| GitBackendError::Remote(format!("tree {} entry is missing `path`", oid)))?;
+            let sha = entry.get("sha").and_then(Json::as_string)
+                .ok_or_else(|| GitBackendError::Remote(format!("tree {} entry `{}` is missing `sha`", oid, name)))?;
+            let entry_oid = Oid::from_str(sha)
+                .map_err(|_| GitBackendError::Remote(format!("tree {} entry `{}` has invalid sha `{}`", oid, name, sha)))?;
+            let is_tree = entry.get("type").and_then(Json::as_string).map_or(false, |t| t == "tree");
+
+            entries.push(TreeEntry {
+                name: name.to_owned(),
+                oid: entry_oid,
+                is_tree: is_tree,
+            });
+        }
+
+        self.cache.lock().unwrap().put(oid, CachedObject::Tree(entries.clone()));
+        Ok(entries)
+    }
+
+    fn resolve_ref(&self, name: &str) -> Result<Oid, GitBackendError> {
+        // Note the singular `/git/ref/{name}`, which returns the single
+        // matching ref object. The plural `/git/refs/{name}` is a
+        // prefix-match listing endpoint and returns a JSON array instead.
+        let json = self.get_json(&format!("/git/ref/{}", name))?;
+        let obj = json.as_object()
+            .ok_or_else(|| GitBackendError::Remote(format!("ref {} response was not a JSON object", name)))?;
+        let object = obj.get("object").and_then(Json::as_object)
+            .ok_or_else(|| GitBackendError::Remote(format!("ref {} response is missing `object`", name)))?;
+        let sha = object.get("sha").and_then(Json::as_string)
+            .ok_or_else(|| GitBackendError::Remote(format!("ref {} response is missing `sha`", name)))?;
+        Oid::from_str(sha)
+            .map_err(|_| GitBackendError::Remote(format!("ref {} has invalid sha `{}`", name, sha)))
+    }
+}
+
+// One blame/Hg-rev source: a blame repo (optionally indexed into a
+// persistent store) and/or a cinnabar-derived Hg map, plus the maps they
+// produced.
+pub struct BlameSource {
     pub blame_repo: Option<Repository>,
 
+    // Persistent store backing `blame_map`, living next to the blame repo's
+    // path. Kept around (rather than dropped once the map is hydrated) so a
+    // future incremental re-index can reuse the open handle.
+    pub blame_store: Option<Db>,
+
     pub blame_map: HashMap<Oid, Oid>, // Maps repo OID to blame_repo OID.
     pub hg_map: HashMap<Oid, String>, // Maps repo OID to Hg rev.
 }
 
+pub struct GitData {
+    pub repo: Box<dyn GitBackend>,
+
+    // Blame/Hg-rev sources in priority order; the first one with a hit for
+    // a given OID wins. The primary source (built from `git_blame_path`/
+    // `cinnabar_path`) comes first, followed by `extra_blame_sources` in the
+    // order they're listed in the config.
+    pub blame_sources: Vec<BlameSource>,
+}
+
 pub struct TreeConfig {
     pub paths: TreeConfigPaths,
     pub git: Option<GitData>,
@@ -51,6 +380,26 @@ pub fn get_git_path(tree_config: &TreeConfig) -> Result<&str, &'static str> {
     }
 }
 
+// Looks up the Hg rev a git OID corresponds to, consulting `git`'s blame
+// sources in priority order and returning the first hit. Works the same
+// regardless of whether a given source's `hg_map` was populated from
+// synthetic blame-commit messages or from git-cinnabar metadata.
+pub fn get_hg_rev(git: &GitData, oid: Oid) -> Option<String> {
+    git.blame_sources.iter()
+        .filter_map(|source| source.hg_map.get(&oid))
+        .next()
+        .cloned()
+}
+
+// Looks up the blame-repo OID a git OID corresponds to, consulting `git`'s
+// blame sources in priority order and returning the first hit.
+pub fn get_blame_oid(git: &GitData, oid: Oid) -> Option<Oid> {
+    git.blame_sources.iter()
+        .filter_map(|source| source.blame_map.get(&oid))
+        .next()
+        .cloned()
+}
+
 pub fn get_hg_root(tree_config: &TreeConfig) -> String {
     // For temporary backwards compatibility, produce the m-c root if
     // there isn't one specified. We can remove this once all relevant
@@ -62,12 +411,58 @@ pub fn get_hg_root(tree_config: &TreeConfig) -> String {
     }
 }
 
-fn index_blame(_repo: &Repository, blame_repo: &Repository) -> (HashMap<Oid, Oid>, HashMap<Oid, String>) {
+fn blame_store_path(git_blame_path: &str) -> String {
+    format!("{}.blame-index", git_blame_path)
+}
+
+fn oid_key(oid: Oid) -> [u8; 20] {
+    let mut key = [0u8; 20];
+    key.copy_from_slice(oid.as_bytes());
+    key
+}
+
+const HEAD_KEY: &'static [u8] = b"head";
+
+// Bring `store`'s "blame" and "hg" trees up to date with `blame_repo`'s
+// current HEAD, walking backwards only as far as the commit we recorded as
+// already indexed last time (or the whole history on first run).
+//
+// The walk hides the recorded HEAD, which excludes it and all of its
+// ancestors, since those were indexed last time. If the blame repo was
+// rewritten (force-pushed) out from under us, the previously recorded HEAD is
+// no longer an ancestor of the new HEAD, so the "blame" and "hg" trees are
+// cleared and the whole history is indexed again.
+fn index_blame(blame_repo: &Repository, git_blame_path: &str, store: &Db) -> Result<(), ConfigError> {
+    let head = blame_repo.head().ok()
+        .and_then(|head_ref| head_ref.target())
+        .ok_or_else(|| ConfigError::BlameRepoHead(git_blame_path.to_owned()))?;
+
+    let blame_tree = store.open_tree("blame").unwrap();
+    let hg_tree = store.open_tree("hg").unwrap();
+    let meta_tree = store.open_tree("meta").unwrap();
+
+    let stored_head = meta_tree.get(HEAD_KEY).unwrap()
+        .and_then(|bytes| Oid::from_bytes(&bytes).ok());
+
     let mut walk = blame_repo.revwalk().unwrap();
-    walk.push_head().unwrap();
+    walk.set_sorting(git2::Sort::TOPOLOGICAL).unwrap();
+    walk.push(head).unwrap();
+
+    match stored_head {
+        Some(stored_head) if stored_head == head => {
+            // Already fully indexed.
+            return Ok(());
+        }
+        Some(stored_head) if blame_repo.graph_descendant_of(head, stored_head).unwrap_or(false) => {
+            walk.hide(stored_head).unwrap();
+        }
+        Some(_) => {
+            blame_tree.clear().unwrap();
+            hg_tree.clear().unwrap();
+        }
+        None => {}
+    }
 
-    let mut blame_map = HashMap::new();
-    let mut hg_map = HashMap::new();
     for r in walk {
         let oid = r.unwrap();
         let commit = blame_repo.find_commit(oid).unwrap();
@@ -75,64 +470,209 @@ fn index_blame(_repo: &Repository, blame_repo: &Repository) -> (HashMap<Oid, Oid
         let msg = commit.message().unwrap();
         let pieces = msg.split_whitespace().collect::<Vec<_>>();
 
-        let orig_oid = Oid::from_str(pieces[1]).unwrap();
-        blame_map.insert(orig_oid, commit.id());
+        if pieces.len() < 2 {
+            return Err(ConfigError::BlameEntry(oid, "message has too few fields".to_owned()));
+        }
+        let orig_oid = Oid::from_str(pieces[1])
+            .map_err(|_| ConfigError::BlameEntry(oid, format!("`{}` is not a valid OID", pieces[1])))?;
+        blame_tree.insert(&oid_key(orig_oid), &oid_key(commit.id())).unwrap();
 
         if pieces.len() > 2 {
+            if pieces.len() < 4 {
+                return Err(ConfigError::BlameEntry(oid, "has an `hg` marker but no rev field".to_owned()));
+            }
             let hg_id = pieces[3].to_owned();
-            hg_map.insert(orig_oid, hg_id);
+            hg_tree.insert(&oid_key(orig_oid), hg_id.as_bytes()).unwrap();
         }
     }
 
+    meta_tree.insert(HEAD_KEY, &oid_key(head)).unwrap();
+    store.flush().unwrap();
+    Ok(())
+}
+
+fn hydrate_blame_maps(store: &Db) -> (HashMap<Oid, Oid>, HashMap<Oid, String>) {
+    let blame_tree = store.open_tree("blame").unwrap();
+    let hg_tree = store.open_tree("hg").unwrap();
+
+    let mut blame_map = HashMap::new();
+    for item in blame_tree.iter() {
+        let (k, v) = item.unwrap();
+        blame_map.insert(Oid::from_bytes(&k).unwrap(), Oid::from_bytes(&v).unwrap());
+    }
+
+    let mut hg_map = HashMap::new();
+    for item in hg_tree.iter() {
+        let (k, v) = item.unwrap();
+        hg_map.insert(Oid::from_bytes(&k).unwrap(), String::from_utf8(v.to_vec()).unwrap());
+    }
+
     (blame_map, hg_map)
 }
 
-pub fn load(config_path: &str, need_indexes: bool) -> Config {
-    let config_file = File::open(config_path).unwrap();
+// Reads the Hg<->Git correspondence straight out of a git-cinnabar-converted
+// clone's metadata, rather than relying on the bespoke blame-commit message
+// convention `index_blame` parses. Cinnabar records its state as a commit
+// pointed to by `refs/cinnabar/metadata`, whose tree has a `git2hg` entry: a
+// git notes tree mapping each git commit OID (as a fanned-out hex path, the
+// usual git-notes layout) to a blob holding the corresponding Hg changeset
+// id.
+fn read_cinnabar_hg_map(cinnabar_repo: &Repository) -> HashMap<Oid, String> {
+    let mut hg_map = HashMap::new();
+
+    let metadata_ref = match cinnabar_repo.find_reference("refs/cinnabar/metadata") {
+        Ok(r) => r,
+        Err(_) => return hg_map,
+    };
+    let metadata_commit = match metadata_ref.peel_to_commit() {
+        Ok(c) => c,
+        Err(_) => return hg_map,
+    };
+    let tree = metadata_commit.tree().unwrap();
+    let git2hg_entry = match tree.get_name("git2hg") {
+        Some(entry) => entry,
+        None => return hg_map,
+    };
+    let git2hg_tree = git2hg_entry.to_object(cinnabar_repo).unwrap().into_tree().unwrap();
+
+    walk_notes_tree(cinnabar_repo, &git2hg_tree, String::new(), &mut hg_map);
+
+    hg_map
+}
+
+fn walk_notes_tree(repo: &Repository, tree: &git2::Tree, prefix: String, hg_map: &mut HashMap<Oid, String>) {
+    for entry in tree.iter() {
+        let name = entry.name().unwrap();
+        let object = entry.to_object(repo).unwrap();
+        match object.kind() {
+            Some(git2::ObjectType::Tree) => {
+                let subtree = object.into_tree().unwrap();
+                walk_notes_tree(repo, &subtree, format!("{}{}", prefix, name), hg_map);
+            }
+            Some(git2::ObjectType::Blob) => {
+                let hex = format!("{}{}", prefix, name);
+                let oid = match Oid::from_str(&hex) {
+                    Ok(oid) => oid,
+                    Err(_) => continue,
+                };
+                let blob = object.into_blob().unwrap();
+                if let Ok(hg_rev) = ::std::str::from_utf8(blob.content()) {
+                    hg_map.insert(oid, hg_rev.trim().to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Builds a single `BlameSource` from a `git_blame_path`/`cinnabar_path` pair,
+// the same shape used for both the tree's primary source and each of its
+// `extra_blame_sources`. `cinnabar_path`, when present, takes priority over
+// `git_blame_path`'s synthetic commit messages for the Hg map.
+fn build_blame_source(
+    git_blame_path: Option<&str>,
+    cinnabar_path: Option<&str>,
+    need_indexes: bool,
+) -> Result<BlameSource, ConfigError> {
+    let (blame_repo, blame_store, blame_map, mut hg_map) = match git_blame_path {
+        Some(git_blame_path) => {
+            let blame_repo = Repository::open(git_blame_path)
+                .map_err(|e| ConfigError::GitOpen(git_blame_path.to_owned(), e))?;
+
+            let (blame_store, blame_map, hg_map) = if need_indexes {
+                let store_path = blame_store_path(git_blame_path);
+                let store = sled::open(&store_path)
+                    .map_err(|e| ConfigError::BlameStoreOpen(store_path, e))?;
+                index_blame(&blame_repo, git_blame_path, &store)?;
+                let (blame_map, hg_map) = hydrate_blame_maps(&store);
+                (Some(store), blame_map, hg_map)
+            } else {
+                (None, HashMap::new(), HashMap::new())
+            };
+
+            (Some(blame_repo), blame_store, blame_map, hg_map)
+        },
+        None => (None, None, HashMap::new(), HashMap::new()),
+    };
+
+    if need_indexes {
+        if let Some(cinnabar_path) = cinnabar_path {
+            let cinnabar_repo = Repository::open(cinnabar_path)
+                .map_err(|e| ConfigError::GitOpen(cinnabar_path.to_owned(), e))?;
+            hg_map = read_cinnabar_hg_map(&cinnabar_repo);
+        }
+    }
+
+    Ok(BlameSource {
+        blame_repo: blame_repo,
+        blame_store: blame_store,
+        blame_map: blame_map,
+        hg_map: hg_map,
+    })
+}
+
+pub fn load(config_path: &str, need_indexes: bool) -> Result<Config, ConfigError> {
+    let config_file = File::open(config_path)
+        .map_err(|e| ConfigError::Io(config_path.to_owned(), e))?;
     let mut reader = BufReader::new(&config_file);
     let mut input = String::new();
-    reader.read_to_string(&mut input).unwrap();
-    let config = Json::from_str(&input).unwrap();
+    reader.read_to_string(&mut input)
+        .map_err(|e| ConfigError::Io(config_path.to_owned(), e))?;
+    let config = Json::from_str(&input)
+        .map_err(|e| ConfigError::JsonParse(config_path.to_owned(), e))?;
 
-    let mut obj = config.as_object().unwrap().clone();
+    let shape_err = |field: &str| ConfigError::Shape { tree: None, field: field.to_owned() };
 
-    let mozsearch_json = obj.remove("mozsearch_path").unwrap();
-    let mozsearch = mozsearch_json.as_string().unwrap();
+    let mut obj = config.as_object().ok_or_else(|| shape_err("<root>"))?.clone();
+
+    let mozsearch_json = obj.remove("mozsearch_path").ok_or_else(|| shape_err("mozsearch_path"))?;
+    let mozsearch = mozsearch_json.as_string().ok_or_else(|| shape_err("mozsearch_path"))?;
+
+    let trees_obj = obj.get("trees").ok_or_else(|| shape_err("trees"))?
+        .as_object().ok_or_else(|| shape_err("trees"))?.clone();
 
-    let trees_obj = obj.get("trees").unwrap().as_object().unwrap().clone();
-    
     let mut trees = BTreeMap::new();
     for (tree_name, tree_config) in trees_obj {
         let mut decoder = json::Decoder::new(tree_config);
-        let paths = TreeConfigPaths::decode(&mut decoder).unwrap();
+        let paths = TreeConfigPaths::decode(&mut decoder)
+            .map_err(|e| ConfigError::Shape { tree: Some(tree_name.clone()), field: format!("{}", e) })?;
 
-        let git = match (&paths.git_path, &paths.git_blame_path) {
-            (&Some(ref git_path), &Some(ref git_blame_path)) => {
-                let repo = Repository::open(&git_path).unwrap();
-                let blame_repo = Repository::open(&git_blame_path).unwrap();
+        // A tree's repo data comes from a local clone (`git_path`) or, if
+        // that's absent, a remote GitHub-style git data API (`github_source`).
+        let backend: Option<Box<dyn GitBackend>> = match (&paths.git_path, &paths.github_source) {
+            (&Some(ref git_path), _) => {
+                let repo = Repository::open(git_path)
+                    .map_err(|e| ConfigError::GitOpen(git_path.clone(), e))?;
+                Some(Box::new(repo))
+            },
+            (&None, &Some(ref github_source)) => Some(Box::new(GitHubBackend::new(github_source.clone()))),
+            (&None, &None) => None,
+        };
 
-                let (blame_map, hg_map) = if need_indexes {
-                    index_blame(&repo, &blame_repo)
-                } else {
-                    (HashMap::new(), HashMap::new())
-                };
+        let git = match backend {
+            Some(repo) => {
+                let mut blame_sources = vec![build_blame_source(
+                    paths.git_blame_path.as_ref().map(String::as_str),
+                    paths.cinnabar_path.as_ref().map(String::as_str),
+                    need_indexes,
+                )?];
+
+                if let Some(ref extra_blame_sources) = paths.extra_blame_sources {
+                    for extra in extra_blame_sources {
+                        blame_sources.push(build_blame_source(
+                            extra.git_blame_path.as_ref().map(String::as_str),
+                            extra.cinnabar_path.as_ref().map(String::as_str),
+                            need_indexes,
+                        )?);
+                    }
+                }
 
                 Some(GitData {
                     repo: repo,
-                    blame_repo: Some(blame_repo),
-                    blame_map: blame_map,
-                    hg_map: hg_map,
+                    blame_sources: blame_sources,
                 })
             },
-            (&Some(ref git_path), &None) => {
-                Some(GitData {
-                    repo: Repository::open(&git_path).unwrap(),
-                    blame_repo: None,
-                    blame_map: HashMap::new(),
-                    hg_map: HashMap::new(),
-                })
-            },
-            _ => None,
+            None => None,
         };
 
         trees.insert(tree_name, TreeConfig {
@@ -141,5 +681,93 @@ pub fn load(config_path: &str, need_indexes: bool) -> Config {
         });
     }
 
-    Config { trees: trees, mozsearch_path: mozsearch.to_owned() }
+    Ok(Config { trees: trees, mozsearch_path: mozsearch.to_owned() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // Creates a blame commit on `repo`'s HEAD following the `<marker> <oid>
+    // [hg <rev>]` convention `index_blame` parses.
+    fn commit_blame_entry(repo: &Repository, parents: &[&git2::Commit], orig_oid: Oid, hg_rev: Option<&str>) -> Oid {
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let msg = match hg_rev {
+            Some(rev) => format!("blame {} hg {}", orig_oid, rev),
+            None => format!("blame {}", orig_oid),
+        };
+        // Move HEAD by hand rather than through `commit`'s update_ref, which
+        // libgit2 refuses when the current tip isn't the first parent (as
+        // with a new root commit standing in for a force-push).
+        let oid = repo.commit(None, &sig, &sig, &msg, &tree, parents).unwrap();
+        repo.set_head_detached(oid).unwrap();
+        oid
+    }
+
+    #[test]
+    fn incremental_index_only_walks_new_commits() {
+        let repo_dir = tempdir().unwrap();
+        let blame_repo = Repository::init(repo_dir.path()).unwrap();
+
+        let orig_a = Oid::from_str("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
+        let first = commit_blame_entry(&blame_repo, &[], orig_a, Some("0000000000000000000000000000000000000000"));
+        let first_commit = blame_repo.find_commit(first).unwrap();
+
+        let store_dir = tempdir().unwrap();
+        let store = sled::open(store_dir.path()).unwrap();
+        let git_blame_path = repo_dir.path().to_str().unwrap();
+
+        index_blame(&blame_repo, git_blame_path, &store).unwrap();
+        let (blame_map, hg_map) = hydrate_blame_maps(&store);
+        assert_eq!(blame_map.get(&orig_a), Some(&first));
+        assert!(hg_map.contains_key(&orig_a));
+
+        // Re-indexing with no new commits on the blame repo is a no-op: the
+        // walk should stop immediately since HEAD is already the stored HEAD.
+        index_blame(&blame_repo, git_blame_path, &store).unwrap();
+        let (blame_map, _) = hydrate_blame_maps(&store);
+        assert_eq!(blame_map.len(), 1);
+
+        // Fast-forward: a new commit descends from the stored HEAD, so only
+        // it should get walked and inserted.
+        let orig_b = Oid::from_str("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb").unwrap();
+        let second = commit_blame_entry(&blame_repo, &[&first_commit], orig_b, None);
+
+        index_blame(&blame_repo, git_blame_path, &store).unwrap();
+        let (blame_map, _) = hydrate_blame_maps(&store);
+        assert_eq!(blame_map.len(), 2);
+        assert_eq!(blame_map.get(&orig_b), Some(&second));
+    }
+
+    #[test]
+    fn rewritten_blame_history_triggers_full_rebuild() {
+        let repo_dir = tempdir().unwrap();
+        let blame_repo = Repository::init(repo_dir.path()).unwrap();
+
+        let orig_a = Oid::from_str("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
+        commit_blame_entry(&blame_repo, &[], orig_a, None);
+
+        let store_dir = tempdir().unwrap();
+        let store = sled::open(store_dir.path()).unwrap();
+        let git_blame_path = repo_dir.path().to_str().unwrap();
+
+        index_blame(&blame_repo, git_blame_path, &store).unwrap();
+
+        // Force-push: a brand new root commit replaces the old history
+        // outright, so the old stored HEAD is no longer an ancestor of the
+        // new one.
+        let orig_b = Oid::from_str("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb").unwrap();
+        let rewritten = commit_blame_entry(&blame_repo, &[], orig_b, None);
+
+        index_blame(&blame_repo, git_blame_path, &store).unwrap();
+        let (blame_map, _) = hydrate_blame_maps(&store);
+
+        // The old entry was dropped by the full rebuild; only the rewritten
+        // history is indexed.
+        assert_eq!(blame_map.len(), 1);
+        assert_eq!(blame_map.get(&orig_b), Some(&rewritten));
+    }
 }